@@ -0,0 +1,56 @@
+//!
+//! Errors thrown by the [`AtomicDrmDevice`](::backend::drm::atomic::AtomicDrmDevice) and
+//! [`AtomicDrmSurface`](::backend::drm::atomic::AtomicDrmSurface).
+//!
+
+use drm::control::{connector, crtc, Mode};
+
+error_chain! {
+    errors {
+        #[doc = "Unable to determine the device id of the drm node"]
+        UnableToGetDeviceId {
+            description("Unable to determine the device id of the drm node"),
+        }
+
+        #[doc = "The underlying drm node reported an error"]
+        DrmDev(source: String) {
+            description("The underlying drm node reported an error"),
+            display("The underlying drm node in {} reported an error", source),
+        }
+
+        #[doc = "The given crtc is already in use by another surface"]
+        CrtcAlreadyInUse(crtc: crtc::Handle) {
+            description("The given crtc is already in use by another surface"),
+            display("The crtc {:?} is already in use by another surface", crtc),
+        }
+
+        #[doc = "The device is currently paused and cannot be used"]
+        DeviceInactive {
+            description("The device is currently paused and cannot be used"),
+        }
+
+        #[doc = "The given mode is not supported by the connector"]
+        ModeNotSuitable(mode: Mode) {
+            description("The given mode is not supported by the connector"),
+            display("The mode {:?} is not supported by the connector", mode),
+        }
+
+        #[doc = "No encoder was found that supports the given crtc for one of the given connectors"]
+        NoSuitableEncoder(connector: connector::Info, crtc: crtc::Handle) {
+            description("No encoder was found that supports the given crtc for one of the given connectors"),
+            display("No encoder for connector {:?} supports the crtc {:?}", connector.handle(), crtc),
+        }
+
+        #[doc = "A `TEST_ONLY` atomic commit rejected the requested configuration"]
+        TestFailed(crtc: crtc::Handle) {
+            description("A test-only atomic commit rejected the requested configuration"),
+            display("The requested configuration for crtc {:?} was rejected by a test commit", crtc),
+        }
+
+        #[doc = "The device does not expose the requested property"]
+        UnknownProperty(name: String) {
+            description("The device does not expose the requested property"),
+            display("The device does not expose the '{}' property", name),
+        }
+    }
+}
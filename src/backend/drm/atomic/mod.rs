@@ -0,0 +1,305 @@
+use super::{DevPath, Device, DeviceHandler, RawDevice, Surface};
+
+use drm::Device as BasicDevice;
+use drm::control::{
+    atomic, connector, crtc, encoder, property, AtomicCommitFlags, Device as ControlDevice, Mode, ResourceInfo,
+};
+use nix::libc::dev_t;
+use nix::sys::stat::fstat;
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::rc::{Rc, Weak};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+
+use crate::backend::drm::props::load_properties;
+
+mod surface;
+pub use self::surface::AtomicDrmSurface;
+use self::surface::State;
+
+pub mod error;
+use self::error::*;
+
+/// An open drm device, that configures crtcs, connectors and planes through a single
+/// atomic commit instead of one legacy ioctl per object.
+///
+/// Implements the same [`Device`]/[`RawDevice`] traits as
+/// [`LegacyDrmDevice`](super::legacy::LegacyDrmDevice), and is the preferred backend on
+/// drivers that advertise `DRM_CLIENT_CAP_ATOMIC` support, as it allows tear-free updates
+/// across multiple crtcs in one go and lets `create_surface` validate a configuration with
+/// a `TEST_ONLY` commit before it is ever shown on screen. Unlike the legacy device,
+/// `process_events` only reports page flips (no connector hotplug callbacks), and its
+/// surfaces have no hardware cursor or overlay plane methods.
+pub struct AtomicDrmDevice<A: AsRawFd + 'static> {
+    dev: Rc<Dev<A>>,
+    dev_id: dev_t,
+    priviledged: bool,
+    active: Arc<AtomicBool>,
+    old_state: HashMap<crtc::Handle, (crtc::Info, Vec<connector::Handle>)>,
+    backends: Rc<RefCell<HashMap<crtc::Handle, Weak<AtomicDrmSurface<A>>>>>,
+    handler: Option<RefCell<Box<DeviceHandler<Device = AtomicDrmDevice<A>>>>>,
+    logger: ::slog::Logger,
+}
+
+pub(in crate::backend::drm) struct Dev<A: AsRawFd + 'static>(A);
+impl<A: AsRawFd + 'static> AsRawFd for Dev<A> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+impl<A: AsRawFd + 'static> BasicDevice for Dev<A> {}
+impl<A: AsRawFd + 'static> ControlDevice for Dev<A> {}
+
+impl<A: AsRawFd + 'static> AtomicDrmDevice<A> {
+    /// Create a new `AtomicDrmDevice` from an open drm node.
+    ///
+    /// Returns an error if the file is no valid drm node, the driver does not support
+    /// atomic modesetting, or context creation was not successful.
+    pub fn new<L>(dev: A, logger: L) -> Result<Self>
+    where
+        L: Into<Option<::slog::Logger>>,
+    {
+        let log = ::slog_or_stdlog(logger).new(o!("smithay_module" => "backend_drm_atomic"));
+
+        let dev_id = fstat(dev.as_raw_fd())
+            .chain_err(|| ErrorKind::UnableToGetDeviceId)?
+            .st_rdev;
+
+        let mut drm = AtomicDrmDevice {
+            dev: Rc::new(Dev(dev)),
+            dev_id,
+            priviledged: true,
+            active: Arc::new(AtomicBool::new(true)),
+            old_state: HashMap::new(),
+            backends: Rc::new(RefCell::new(HashMap::new())),
+            handler: None,
+            logger: log.clone(),
+        };
+
+        info!(log, "AtomicDrmDevice initializing");
+
+        if drm.set_master().is_err() {
+            warn!(log, "Unable to become drm master, assuming unpriviledged mode");
+            drm.priviledged = false;
+        };
+
+        drm.set_client_capability(::drm::ClientCapability::Atomic, true)
+            .chain_err(|| ErrorKind::DrmDev(format!("Driver does not support atomic modesetting on {:?}", drm.dev_path())))?;
+
+        let res_handles = drm.resource_handles().chain_err(|| {
+            ErrorKind::DrmDev(format!("Error loading drm resources on {:?}", drm.dev_path()))
+        })?;
+        for &con in res_handles.connectors() {
+            let con_info = connector::Info::load_from_device(&drm, con).chain_err(|| {
+                ErrorKind::DrmDev(format!("Error loading connector info on {:?}", drm.dev_path()))
+            })?;
+            if let Some(enc) = con_info.current_encoder() {
+                let enc_info = encoder::Info::load_from_device(&drm, enc).chain_err(|| {
+                    ErrorKind::DrmDev(format!("Error loading encoder info on {:?}", drm.dev_path()))
+                })?;
+                if let Some(crtc) = enc_info.current_crtc() {
+                    let info = crtc::Info::load_from_device(&drm, crtc).chain_err(|| {
+                        ErrorKind::DrmDev(format!("Error loading crtc info on {:?}", drm.dev_path()))
+                    })?;
+                    drm.old_state
+                        .entry(crtc)
+                        .or_insert((info, Vec::new()))
+                        .1
+                        .push(con);
+                }
+            }
+        }
+
+        Ok(drm)
+    }
+
+    pub fn dev_id(&self) -> dev_t {
+        self.dev_id
+    }
+}
+
+impl<A: AsRawFd + 'static> AsRawFd for AtomicDrmDevice<A> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.dev.0.as_raw_fd()
+    }
+}
+
+impl<A: AsRawFd + 'static> BasicDevice for AtomicDrmDevice<A> {}
+impl<A: AsRawFd + 'static> ControlDevice for AtomicDrmDevice<A> {}
+
+impl<A: AsRawFd + 'static> Device for AtomicDrmDevice<A> {
+    type Surface = AtomicDrmSurface<A>;
+    type Return = Rc<AtomicDrmSurface<A>>;
+    type Error = Error;
+
+    fn set_handler(&mut self, handler: impl DeviceHandler<Device = Self> + 'static) {
+        self.handler = Some(RefCell::new(Box::new(handler)));
+    }
+
+    fn clear_handler(&mut self) {
+        let _ = self.handler.take();
+    }
+
+    fn create_surface(
+        &mut self,
+        crtc: crtc::Handle,
+        mode: Mode,
+        connectors: impl Into<<Self::Surface as Surface>::Connectors>,
+    ) -> Result<Rc<AtomicDrmSurface<A>>> {
+        if self.backends.borrow().contains_key(&crtc) {
+            bail!(ErrorKind::CrtcAlreadyInUse(crtc));
+        }
+
+        if !self.active.load(Ordering::SeqCst) {
+            bail!(ErrorKind::DeviceInactive);
+        }
+
+        let connectors: HashSet<_> = connectors.into();
+        let mut conn_props = Vec::new();
+        for connector in &connectors {
+            let con_info = connector::Info::load_from_device(self, *connector).chain_err(|| {
+                ErrorKind::DrmDev(format!("Error loading connector info on {:?}", self.dev_path()))
+            })?;
+
+            if !con_info.modes().contains(&mode) {
+                bail!(ErrorKind::ModeNotSuitable(mode));
+            }
+
+            let encoders = con_info
+                .encoders()
+                .iter()
+                .map(|encoder| {
+                    encoder::Info::load_from_device(self, *encoder).chain_err(|| {
+                        ErrorKind::DrmDev(format!("Error loading encoder info on {:?}", self.dev_path()))
+                    })
+                }).collect::<Result<Vec<encoder::Info>>>()?;
+
+            let resource_handles = self.resource_handles().chain_err(|| {
+                ErrorKind::DrmDev(format!("Error loading drm resources on {:?}", self.dev_path()))
+            })?;
+            if !encoders
+                .iter()
+                .map(|encoder| encoder.possible_crtcs())
+                .any(|crtc_list| resource_handles.filter_crtcs(crtc_list).contains(&crtc))
+            {
+                bail!(ErrorKind::NoSuitableEncoder(con_info, crtc))
+            }
+
+            conn_props.push((*connector, load_properties(self, *connector)));
+        }
+
+        let crtc_props = load_properties(self, crtc);
+        let logger = self.logger.new(o!("crtc" => format!("{:?}", crtc)));
+
+        let state = State {
+            mode,
+            connectors: connectors.clone(),
+        };
+
+        // Before handing out the surface, make sure the kernel actually accepts this
+        // configuration by running the very same request as a `TEST_ONLY` atomic commit.
+        let mut req = atomic::AtomicModeReq::new();
+        if let Some(mode_id) = crtc_props.get("MODE_ID") {
+            let blob = self.dev.create_property_blob(&mode).chain_err(|| {
+                ErrorKind::DrmDev(format!("Error creating mode blob on {:?}", self.dev_path()))
+            })?;
+            req.add_property(crtc, mode_id, property::Value::Blob(blob));
+        }
+        if let Some(active) = crtc_props.get("ACTIVE") {
+            req.add_property(crtc, active, property::Value::Boolean(true));
+        }
+        for (conn, props) in &conn_props {
+            if let Some(crtc_id) = props.get("CRTC_ID") {
+                req.add_property(*conn, crtc_id, property::Value::CRTC(Some(crtc)));
+            }
+        }
+        atomic::commit(self, &[AtomicCommitFlags::AllowModeset, AtomicCommitFlags::TestOnly], req)
+            .chain_err(|| ErrorKind::TestFailed(crtc))?;
+
+        let backend = Rc::new(AtomicDrmSurface {
+            dev: self.dev.clone(),
+            crtc,
+            crtc_props,
+            conn_props: RwLock::new(conn_props),
+            state: RwLock::new(state.clone()),
+            pending: RwLock::new(state),
+            logger,
+        });
+
+        self.backends.borrow_mut().insert(crtc, Rc::downgrade(&backend));
+        Ok(backend)
+    }
+
+    fn process_events(&mut self) {
+        match crtc::receive_events(self) {
+            Ok(events) => for event in events {
+                if let crtc::Event::PageFlip(event) = event {
+                    if self.active.load(Ordering::SeqCst) {
+                        if let Some(backend) = self.backends.borrow().get(&event.crtc).iter().flat_map(|x| x.upgrade()).next() {
+                            trace!(self.logger, "Handling event for backend {:?}", event.crtc);
+                            if let Some(handler) = self.handler.as_ref() {
+                                handler.borrow_mut().vblank(&backend);
+                            }
+                        } else {
+                            self.backends.borrow_mut().remove(&event.crtc);
+                        }
+                    }
+                }
+            },
+            Err(err) => if let Some(handler) = self.handler.as_ref() {
+                handler.borrow_mut().error(ResultExt::<()>::chain_err(Err(err), ||
+                    ErrorKind::DrmDev(format!("Error processing drm events on {:?}", self.dev_path()))
+                ).unwrap_err());
+            }
+        }
+    }
+}
+
+impl<A: AsRawFd + 'static> RawDevice for AtomicDrmDevice<A> {
+    type Surface = AtomicDrmSurface<A>;
+}
+
+impl<A: AsRawFd + 'static> Drop for AtomicDrmDevice<A> {
+    fn drop(&mut self) {
+        self.backends.borrow_mut().clear();
+        if Rc::strong_count(&self.dev) > 1 {
+            panic!("Pending DrmBackends. You need to free all backends before the DrmDevice gets destroyed");
+        }
+        if self.active.load(Ordering::SeqCst) {
+            // Restore the state we captured when opening the device via one atomic commit
+            // per crtc, rather than `crtc::set` as the legacy backend does.
+            for (handle, (info, connectors)) in self.old_state.drain() {
+                let crtc_props = load_properties(&*self.dev, handle);
+                let mut req = atomic::AtomicModeReq::new();
+                if let Some(mode_id) = crtc_props.get("MODE_ID") {
+                    if let Ok(blob) = self.dev.create_property_blob(&info.mode()) {
+                        req.add_property(handle, mode_id, property::Value::Blob(blob));
+                    }
+                }
+                if let Some(active) = crtc_props.get("ACTIVE") {
+                    req.add_property(handle, active, property::Value::Boolean(true));
+                }
+                if let (Some(fb_id), Some(fb)) = (crtc_props.get("FB_ID"), info.fb()) {
+                    req.add_property(handle, fb_id, property::Value::Framebuffer(Some(fb)));
+                }
+                for conn in &connectors {
+                    let conn_props = load_properties(&*self.dev, *conn);
+                    if let Some(crtc_id) = conn_props.get("CRTC_ID") {
+                        req.add_property(*conn, crtc_id, property::Value::CRTC(Some(handle)));
+                    }
+                }
+                if let Err(err) = atomic::commit(&*self.dev, &[AtomicCommitFlags::AllowModeset], req) {
+                    error!(self.logger, "Failed to reset crtc ({:?}). Error: {}", handle, err);
+                }
+            }
+            if self.priviledged {
+                if let Err(err) = self.drop_master() {
+                    error!(self.logger, "Failed to drop drm master state. Error: {}", err);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,196 @@
+use crate::backend::drm::props::{get_property_value, load_properties, PropMapping};
+use super::Dev;
+use crate::backend::drm::{DevPath, RawSurface, Surface};
+
+use drm::control::{atomic, connector, crtc, property, AtomicCommitFlags, Device as ControlDevice, Mode};
+
+use std::collections::HashSet;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::rc::Rc;
+use std::sync::RwLock;
+
+use super::error::*;
+
+#[derive(Clone, PartialEq)]
+pub(in crate::backend::drm::atomic) struct State {
+    pub(in crate::backend::drm::atomic) mode: Mode,
+    pub(in crate::backend::drm::atomic) connectors: HashSet<connector::Handle>,
+}
+
+/// Open crtc of an [`AtomicDrmDevice`](super::AtomicDrmDevice), driven by atomic commits
+/// instead of the legacy `drmModeSetCrtc` ioctl.
+pub struct AtomicDrmSurface<A: AsRawFd + 'static> {
+    pub(in crate::backend::drm::atomic) dev: Rc<Dev<A>>,
+    pub(in crate::backend::drm::atomic) crtc: crtc::Handle,
+    pub(in crate::backend::drm::atomic) crtc_props: PropMapping,
+    /// Property mappings for the connectors currently attached to `crtc`.
+    ///
+    /// Rebuilt on every [`commit`](RawSurface::commit) from `pending.connectors`, since
+    /// `add_connector`/`remove_connector` only update the pending state and this is what
+    /// actually drives which connectors get a `CRTC_ID` in the next atomic request.
+    pub(in crate::backend::drm::atomic) conn_props: RwLock<Vec<(connector::Handle, PropMapping)>>,
+    pub(in crate::backend::drm::atomic) state: RwLock<State>,
+    pub(in crate::backend::drm::atomic) pending: RwLock<State>,
+    pub(in crate::backend::drm::atomic) logger: ::slog::Logger,
+}
+
+impl<A: AsRawFd + 'static> AsRawFd for AtomicDrmSurface<A> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.dev.as_raw_fd()
+    }
+}
+
+impl<A: AsRawFd + 'static> AtomicDrmSurface<A> {
+    /// Looks up `name` among this surface's crtc properties.
+    pub(in crate::backend::drm::atomic) fn crtc_prop(&self, name: &str) -> Result<property::Handle> {
+        self.crtc_props
+            .get(name)
+            .ok_or_else(|| ErrorKind::UnknownProperty(name.to_string()).into())
+    }
+
+    /// Builds and submits an atomic commit covering `req`, optionally as a dry-run
+    /// (`TEST_ONLY`) or a full modeset (`ALLOW_MODESET`).
+    fn atomic_commit(&self, req: atomic::AtomicModeReq, flags: &[AtomicCommitFlags]) -> Result<()> {
+        atomic::commit(&*self.dev, flags, req)
+            .chain_err(|| ErrorKind::DrmDev(format!("Error submitting atomic commit on {:?}", self.dev_path())))
+    }
+
+    /// Whether any connector currently driven by this surface advertises adaptive sync
+    /// support in its EDID (the `vrr_capable` connector property).
+    pub fn supports_vrr(&self) -> bool {
+        self.conn_props
+            .read()
+            .unwrap()
+            .iter()
+            .any(|(conn, _)| get_property_value(&*self.dev, *conn, "vrr_capable") == Some(1))
+    }
+
+    /// Enables or disables variable refresh rate on this surface's crtc via the
+    /// `VRR_ENABLED` property.
+    ///
+    /// Once enabled, flips submitted from [`page_flip`](RawSurface::page_flip) are
+    /// presented as soon as the client buffer is ready instead of being latched to the
+    /// fixed mode's vblank. Fails if the driver does not expose the property, so
+    /// compositors can fall back gracefully.
+    pub fn set_vrr(&self, enabled: bool) -> Result<()> {
+        let prop = self.crtc_prop("VRR_ENABLED")?;
+        let mut req = atomic::AtomicModeReq::new();
+        req.add_property(self.crtc, prop, property::Value::Boolean(enabled));
+        self.atomic_commit(req, &[AtomicCommitFlags::Nonblock])
+    }
+}
+
+impl<A: AsRawFd + 'static> Surface for AtomicDrmSurface<A> {
+    type Connectors = HashSet<connector::Handle>;
+    type Error = Error;
+
+    fn crtc(&self) -> crtc::Handle {
+        self.crtc
+    }
+
+    fn current_connectors(&self) -> HashSet<connector::Handle> {
+        self.state.read().unwrap().connectors.clone()
+    }
+
+    fn pending_connectors(&self) -> HashSet<connector::Handle> {
+        self.pending.read().unwrap().connectors.clone()
+    }
+
+    fn add_connector(&self, connector: connector::Handle) -> Result<()> {
+        self.pending.write().unwrap().connectors.insert(connector);
+        Ok(())
+    }
+
+    fn remove_connector(&self, connector: connector::Handle) -> Result<()> {
+        self.pending.write().unwrap().connectors.remove(&connector);
+        Ok(())
+    }
+
+    fn current_mode(&self) -> Mode {
+        self.state.read().unwrap().mode
+    }
+
+    fn pending_mode(&self) -> Mode {
+        self.pending.read().unwrap().mode
+    }
+
+    fn use_mode(&self, mode: Mode) -> Result<()> {
+        self.pending.write().unwrap().mode = mode;
+        Ok(())
+    }
+}
+
+impl<A: AsRawFd + 'static> RawSurface for AtomicDrmSurface<A> {
+    fn commit_pending(&self) -> bool {
+        *self.pending.read().unwrap() != *self.state.read().unwrap()
+    }
+
+    fn commit(&self) -> Result<()> {
+        let pending = self.pending.read().unwrap().clone();
+
+        // Gather the full set of crtc/connector properties touched by this modeset into a
+        // single request, rather than issuing one legacy ioctl per object.
+        let mut req = atomic::AtomicModeReq::new();
+        let blob = self
+            .dev
+            .create_property_blob(&pending.mode)
+            .chain_err(|| ErrorKind::DrmDev(format!("Error creating mode blob on {:?}", self.dev_path())))?;
+
+        req.add_property(self.crtc, self.crtc_prop("MODE_ID")?, property::Value::Blob(blob));
+        req.add_property(self.crtc, self.crtc_prop("ACTIVE")?, property::Value::Boolean(true));
+
+        let previous: HashSet<connector::Handle> = self
+            .conn_props
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(conn, _)| *conn)
+            .collect();
+
+        // detach any connector that was attached before but isn't part of the pending set
+        // anymore, so `remove_connector` actually takes effect on the next commit
+        for conn in previous.difference(&pending.connectors) {
+            let props = load_properties(&*self.dev, *conn);
+            if let Some(crtc_id) = props.get("CRTC_ID") {
+                req.add_property(*conn, crtc_id, property::Value::CRTC(None));
+            }
+        }
+
+        // rebuild the property mappings from the pending connector set, so a connector
+        // added at runtime via `add_connector` gets its properties resolved too. Kept in a
+        // local variable until the commit below actually succeeds, so a failed commit (which
+        // leaves the old configuration on screen) doesn't desync `conn_props` from reality.
+        let new_conn_props: Vec<(connector::Handle, PropMapping)> = pending
+            .connectors
+            .iter()
+            .map(|conn| (*conn, load_properties(&*self.dev, *conn)))
+            .collect();
+
+        for (conn, props) in &new_conn_props {
+            if let Some(crtc_id) = props.get("CRTC_ID") {
+                req.add_property(*conn, crtc_id, property::Value::CRTC(Some(self.crtc)));
+            }
+        }
+
+        // A full modeset needs `ALLOW_MODESET`; a plain flip of an otherwise identical
+        // configuration does not and is done via `page_flip` instead.
+        self.atomic_commit(req, &[AtomicCommitFlags::AllowModeset])?;
+
+        *self.conn_props.write().unwrap() = new_conn_props;
+        *self.state.write().unwrap() = pending;
+        Ok(())
+    }
+
+    fn page_flip(&self, framebuffer: u32) -> Result<()> {
+        trace!(self.logger, "Queueing atomic page flip for crtc {:?}", self.crtc);
+
+        let mut req = atomic::AtomicModeReq::new();
+        req.add_property(
+            self.crtc,
+            self.crtc_prop("FB_ID")?,
+            property::Value::Framebuffer(Some(framebuffer)),
+        );
+
+        self.atomic_commit(req, &[AtomicCommitFlags::PageFlipEvent, AtomicCommitFlags::Nonblock])
+    }
+}
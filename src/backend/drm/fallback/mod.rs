@@ -0,0 +1,55 @@
+//!
+//! Helper to pick between the [`atomic`](super::atomic) and [`legacy`](super::legacy) backend
+//! at device-open time, so callers don't have to probe `DRM_CLIENT_CAP_ATOMIC` themselves.
+//!
+
+use super::atomic::AtomicDrmDevice;
+use super::legacy::LegacyDrmDevice;
+
+use std::os::unix::io::AsRawFd;
+
+/// Either an [`AtomicDrmDevice`] or a [`LegacyDrmDevice`], chosen once at open time.
+///
+/// Most callers should try [`FallbackDevice::new`] first, which prefers the atomic
+/// backend and transparently falls back to the legacy one on drivers that do not
+/// support atomic modesetting. `FallbackDevice` does not itself implement
+/// [`Device`](super::Device): the two backends' surfaces differ enough (atomic commits
+/// vs. per-object ioctls) that callers still need to `match` on `Atomic`/`Legacy` to get
+/// at a concrete device before calling `create_surface`/`process_events`.
+pub enum FallbackDevice<A: AsRawFd + 'static> {
+    /// The device is being driven through atomic commits.
+    Atomic(AtomicDrmDevice<A>),
+    /// The device is being driven through the legacy modesetting ioctls.
+    Legacy(LegacyDrmDevice<A>),
+}
+
+impl<A: AsRawFd + 'static> FallbackDevice<A> {
+    /// Opens `dev`, preferring the atomic backend and falling back to the legacy one if
+    /// the driver rejects atomic modesetting.
+    pub fn new<L>(dev: A, logger: L) -> super::legacy::error::Result<Self>
+    where
+        A: Clone,
+        L: Into<Option<::slog::Logger>> + Clone,
+    {
+        match AtomicDrmDevice::new(dev.clone(), logger.clone()) {
+            Ok(atomic) => Ok(FallbackDevice::Atomic(atomic)),
+            Err(_) => Ok(FallbackDevice::Legacy(LegacyDrmDevice::new(dev, logger)?)),
+        }
+    }
+
+    /// Explicitly open the device with the atomic backend, bypassing auto-detection.
+    pub fn new_atomic<L>(dev: A, logger: L) -> super::atomic::error::Result<Self>
+    where
+        L: Into<Option<::slog::Logger>>,
+    {
+        Ok(FallbackDevice::Atomic(AtomicDrmDevice::new(dev, logger)?))
+    }
+
+    /// Explicitly open the device with the legacy backend, bypassing auto-detection.
+    pub fn new_legacy<L>(dev: A, logger: L) -> super::legacy::error::Result<Self>
+    where
+        L: Into<Option<::slog::Logger>>,
+    {
+        Ok(FallbackDevice::Legacy(LegacyDrmDevice::new(dev, logger)?))
+    }
+}
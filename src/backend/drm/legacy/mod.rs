@@ -1,7 +1,7 @@
 use super::{Device, RawDevice, Surface, DeviceHandler, DevPath};
 
 use drm::Device as BasicDevice;
-use drm::control::{crtc, connector, encoder, Device as ControlDevice, Mode, ResourceInfo};
+use drm::control::{crtc, connector, encoder, plane, Device as ControlDevice, Mode, ResourceInfo};
 use nix::libc::dev_t;
 use nix::sys::stat::fstat;
 
@@ -28,6 +28,7 @@ pub struct LegacyDrmDevice<A: AsRawFd + 'static> {
     priviledged: bool,
     active: Arc<AtomicBool>,
     old_state: HashMap<crtc::Handle, (crtc::Info, Vec<connector::Handle>)>,
+    connector_state: HashMap<connector::Handle, connector::State>,
     backends: Rc<RefCell<HashMap<crtc::Handle, Weak<LegacyDrmSurface<A>>>>>,
     handler: Option<RefCell<Box<DeviceHandler<Device=LegacyDrmDevice<A>>>>>,
     logger: ::slog::Logger,
@@ -64,6 +65,7 @@ impl<A: AsRawFd + 'static> LegacyDrmDevice<A> {
             priviledged: true,
             active: Arc::new(AtomicBool::new(true)),
             old_state: HashMap::new(),
+            connector_state: HashMap::new(),
             backends: Rc::new(RefCell::new(HashMap::new())),
             handler: None,
             logger: log.clone(),
@@ -84,6 +86,7 @@ impl<A: AsRawFd + 'static> LegacyDrmDevice<A> {
             let con_info = connector::Info::load_from_device(&drm, con).chain_err(|| {
                 ErrorKind::DrmDev(format!("Error loading connector info on {:?}", drm.dev_path()))
             })?;
+            drm.connector_state.insert(con, con_info.state());
             if let Some(enc) = con_info.current_encoder() {
                 let enc_info = encoder::Info::load_from_device(&drm, enc).chain_err(|| {
                     ErrorKind::DrmDev(format!("Error loading encoder info on {:?}", drm.dev_path()))
@@ -107,6 +110,70 @@ impl<A: AsRawFd + 'static> LegacyDrmDevice<A> {
     pub fn dev_id(&self) -> dev_t {
         self.dev_id
     }
+
+    /// Re-reads the connection status of every connector and reports additions/removals
+    /// to the set handler.
+    ///
+    /// Real hotplug detection needs a udev `HOTPLUG`/`HOTPLUG_CONNECTOR` uevent on the drm
+    /// node to tell the caller *that* something changed; this tree has no udev integration
+    /// to deliver that, so for now we pay for a `resource_handles()` plus one
+    /// `connector::Info::load_from_device()` per connector on every `process_events` call
+    /// instead, and diff against the previously known state to find out *what* changed.
+    /// That is a real per-call ioctl cost, not free polling, so once a uevent source is
+    /// wired up this should only run when woken by that, not unconditionally here.
+    fn scan_connectors(&mut self) {
+        let res_handles = match self.resource_handles() {
+            Ok(handles) => handles,
+            Err(err) => {
+                warn!(self.logger, "Failed to re-scan connectors: {}", err);
+                return;
+            }
+        };
+
+        let mut seen = HashSet::new();
+        for &con in res_handles.connectors() {
+            seen.insert(con);
+            let state = match connector::Info::load_from_device(self, con) {
+                Ok(info) => info.state(),
+                Err(err) => {
+                    warn!(self.logger, "Failed to load connector {:?}: {}", con, err);
+                    continue;
+                }
+            };
+
+            match self.connector_state.insert(con, state) {
+                Some(old) if old == state => {}
+                old => {
+                    trace!(self.logger, "Connector {:?} changed state to {:?}", con, state);
+                    if let Some(handler) = self.handler.as_ref() {
+                        if state == connector::State::Connected {
+                            handler.borrow_mut().connector_added(con, state);
+                        } else if old == Some(connector::State::Connected) {
+                            handler.borrow_mut().connector_removed(con, state);
+                        }
+                    }
+                }
+            }
+        }
+
+        // Anything we previously knew about but did not see this time has been unplugged
+        // together with its connector (e.g. a USB-C dock going away).
+        let vanished: Vec<_> = self
+            .connector_state
+            .keys()
+            .filter(|con| !seen.contains(con))
+            .cloned()
+            .collect();
+        for con in vanished {
+            let state = self.connector_state.remove(&con).unwrap();
+            if state != connector::State::Connected {
+                continue;
+            }
+            if let Some(handler) = self.handler.as_ref() {
+                handler.borrow_mut().connector_removed(con, state);
+            }
+        }
+    }
 }
 
 impl<A: AsRawFd + 'static> AsRawFd for LegacyDrmDevice<A> {
@@ -121,6 +188,7 @@ impl<A: AsRawFd + 'static> ControlDevice for LegacyDrmDevice<A> {}
 impl<A: AsRawFd + 'static> Device for LegacyDrmDevice<A> {
     type Surface = LegacyDrmSurface<A>;
     type Return = Rc<LegacyDrmSurface<A>>;
+    type Error = Error;
 
     fn set_handler(&mut self, handler: impl DeviceHandler<Device=Self> + 'static) {
         self.handler = Some(RefCell::new(Box::new(handler)));
@@ -181,7 +249,29 @@ impl<A: AsRawFd + 'static> Device for LegacyDrmDevice<A> {
 
         // configuration is valid, the kernel will figure out the rest
         let logger = self.logger.new(o!("crtc" => format!("{:?}", crtc)));
-        
+
+        // find the planes the kernel lets us use on this crtc, so the surface can offload
+        // video compositing to scanout hardware. Universal planes are not enabled on this
+        // device, so `plane_handles()` lists overlay planes only (no cursor or primary).
+        let plane_handles = self.plane_handles().chain_err(|| {
+            ErrorKind::DrmDev(format!("Error loading planes on {:?}", self.dev_path()))
+        })?;
+        let resource_handles = self.resource_handles().chain_err(|| {
+            ErrorKind::DrmDev(format!("Error loading drm resources on {:?}", self.dev_path()))
+        })?;
+        let mut planes = Vec::new();
+        for &plane in plane_handles.planes() {
+            let plane_info = plane::Info::load_from_device(self, plane).chain_err(|| {
+                ErrorKind::DrmDev(format!("Error loading plane info on {:?}", self.dev_path()))
+            })?;
+            if resource_handles
+                .filter_crtcs(plane_info.possible_crtcs())
+                .contains(&crtc)
+            {
+                planes.push(plane);
+            }
+        }
+
         let state = State {
             mode,
             connectors,
@@ -190,6 +280,9 @@ impl<A: AsRawFd + 'static> Device for LegacyDrmDevice<A> {
         let backend = Rc::new(LegacyDrmSurface {
             dev: self.dev.clone(),
             crtc,
+            planes,
+            cursor: RwLock::new(None),
+            plane_fbs: RwLock::new(HashMap::new()),
             state: RwLock::new(state.clone()),
             pending: RwLock::new(state),
             logger,
@@ -198,8 +291,10 @@ impl<A: AsRawFd + 'static> Device for LegacyDrmDevice<A> {
         self.backends.borrow_mut().insert(crtc, Rc::downgrade(&backend));
         Ok(backend)
     }
-    
+
     fn process_events(&mut self) {
+        self.scan_connectors();
+
         match crtc::receive_events(self) {
             Ok(events) => for event in events {
                 if let crtc::Event::PageFlip(event) = event {
@@ -0,0 +1,246 @@
+use super::Dev;
+use crate::backend::drm::props::{get_property_value, load_properties};
+use crate::backend::drm::{DevPath, RawSurface, Surface};
+
+use drm::buffer::Buffer;
+use drm::control::{connector, crtc, framebuffer, plane, Device as ControlDevice, Mode, ResourceInfo};
+use drm::control::PageFlipFlags;
+
+use std::collections::{HashMap, HashSet};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::rc::Rc;
+use std::sync::RwLock;
+
+use super::error::*;
+
+#[derive(Clone, PartialEq)]
+pub(in crate::backend::drm::legacy) struct State {
+    pub(in crate::backend::drm::legacy) mode: Mode,
+    pub(in crate::backend::drm::legacy) connectors: HashSet<connector::Handle>,
+}
+
+/// Open crtc of a [`LegacyDrmDevice`](super::LegacyDrmDevice), that can be used for scan-out.
+pub struct LegacyDrmSurface<A: AsRawFd + 'static> {
+    pub(in crate::backend::drm::legacy) dev: Rc<Dev<A>>,
+    pub(in crate::backend::drm::legacy) crtc: crtc::Handle,
+    /// Overlay planes the kernel reports as usable with `crtc`.
+    pub(in crate::backend::drm::legacy) planes: Vec<plane::Handle>,
+    /// Hotspot of the currently set hardware cursor image, if any, applied as an offset
+    /// to the position passed to `move_cursor`.
+    pub(in crate::backend::drm::legacy) cursor: RwLock<Option<(u32, u32)>>,
+    /// Framebuffer last handed to [`set_plane`](Self::set_plane) for each plane, so the
+    /// previous one can be torn down instead of leaking a kernel framebuffer object every
+    /// time a new frame is pushed to that plane.
+    pub(in crate::backend::drm::legacy) plane_fbs: RwLock<HashMap<plane::Handle, framebuffer::Handle>>,
+    pub(in crate::backend::drm::legacy) state: RwLock<State>,
+    pub(in crate::backend::drm::legacy) pending: RwLock<State>,
+    pub(in crate::backend::drm::legacy) logger: ::slog::Logger,
+}
+
+impl<A: AsRawFd + 'static> AsRawFd for LegacyDrmSurface<A> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.dev.as_raw_fd()
+    }
+}
+
+impl<A: AsRawFd + 'static> Surface for LegacyDrmSurface<A> {
+    type Connectors = HashSet<connector::Handle>;
+    type Error = Error;
+
+    fn crtc(&self) -> crtc::Handle {
+        self.crtc
+    }
+
+    fn current_connectors(&self) -> HashSet<connector::Handle> {
+        self.state.read().unwrap().connectors.clone()
+    }
+
+    fn pending_connectors(&self) -> HashSet<connector::Handle> {
+        self.pending.read().unwrap().connectors.clone()
+    }
+
+    fn add_connector(&self, connector: connector::Handle) -> Result<()> {
+        self.pending.write().unwrap().connectors.insert(connector);
+        Ok(())
+    }
+
+    fn remove_connector(&self, connector: connector::Handle) -> Result<()> {
+        self.pending.write().unwrap().connectors.remove(&connector);
+        Ok(())
+    }
+
+    fn current_mode(&self) -> Mode {
+        self.state.read().unwrap().mode
+    }
+
+    fn pending_mode(&self) -> Mode {
+        self.pending.read().unwrap().mode
+    }
+
+    fn use_mode(&self, mode: Mode) -> Result<()> {
+        self.pending.write().unwrap().mode = mode;
+        Ok(())
+    }
+}
+
+impl<A: AsRawFd + 'static> RawSurface for LegacyDrmSurface<A> {
+    fn commit_pending(&self) -> bool {
+        *self.pending.read().unwrap() != *self.state.read().unwrap()
+    }
+
+    fn commit(&self) -> Result<()> {
+        let pending = self.pending.read().unwrap().clone();
+        let connectors = pending.connectors.iter().cloned().collect::<Vec<_>>();
+
+        crtc::set(
+            &*self.dev,
+            self.crtc,
+            None,
+            &connectors,
+            (0, 0),
+            Some(pending.mode),
+        ).chain_err(|| ErrorKind::DrmDev(format!("Error setting crtc on {:?}", self.dev_path())))?;
+
+        *self.state.write().unwrap() = pending;
+        Ok(())
+    }
+
+    fn page_flip(&self, framebuffer: u32) -> Result<()> {
+        trace!(self.logger, "Queueing page flip for crtc {:?}", self.crtc);
+        crtc::page_flip(&*self.dev, self.crtc, framebuffer, &[PageFlipFlags::PageFlipEvent])
+            .chain_err(|| ErrorKind::DrmDev(format!("Error queueing page flip on {:?}", self.dev_path())))
+    }
+}
+
+impl<A: AsRawFd + 'static> LegacyDrmSurface<A> {
+    /// Overlay planes the kernel reports as usable with this surface's crtc, in addition
+    /// to the primary plane already driven by [`commit`](RawSurface::commit) and
+    /// [`page_flip`](RawSurface::page_flip). The cursor plane is not listed; use
+    /// [`set_cursor`](Self::set_cursor) for it.
+    pub fn planes(&self) -> &[plane::Handle] {
+        &self.planes
+    }
+
+    /// Uploads `buffer` as the hardware cursor image, with its hotspot at `hotspot`
+    /// (relative to the image's top-left corner). The cursor is not moved here; the new
+    /// hotspot is applied from the next [`move_cursor`](Self::move_cursor) call on.
+    ///
+    /// Maps to `drmModeSetCursor2`.
+    pub fn set_cursor<B: Buffer>(&self, buffer: &B, hotspot: (u32, u32)) -> Result<()> {
+        crtc::set_cursor2(
+            &*self.dev,
+            self.crtc,
+            Some(buffer.handle()),
+            buffer.size(),
+            hotspot,
+        ).chain_err(|| ErrorKind::DrmDev(format!("Error setting cursor on {:?}", self.dev_path())))?;
+
+        *self.cursor.write().unwrap() = Some(hotspot);
+        Ok(())
+    }
+
+    /// Hides a previously set hardware cursor image.
+    pub fn clear_cursor(&self) -> Result<()> {
+        crtc::clear_cursor(&*self.dev, self.crtc)
+            .chain_err(|| ErrorKind::DrmDev(format!("Error clearing cursor on {:?}", self.dev_path())))?;
+        *self.cursor.write().unwrap() = None;
+        Ok(())
+    }
+
+    /// Moves the hardware cursor so its hotspot lands on `position` (crtc coordinates).
+    ///
+    /// Maps to `drmModeMoveCursor`.
+    pub fn move_cursor(&self, position: (i32, i32)) -> Result<()> {
+        let (hot_x, hot_y) = self.cursor.read().unwrap().unwrap_or((0, 0));
+        crtc::move_cursor(
+            &*self.dev,
+            self.crtc,
+            (position.0 - hot_x as i32, position.1 - hot_y as i32),
+        ).chain_err(|| ErrorKind::DrmDev(format!("Error moving cursor on {:?}", self.dev_path())))
+    }
+
+    /// Assigns `buffer` to `plane`, sampling the `src` rectangle (in the buffer) onto the
+    /// `dst` rectangle (in crtc coordinates), for offloading e.g. a video overlay to
+    /// scanout hardware instead of compositing it in GL.
+    ///
+    /// `plane` must be one of the handles returned by [`planes`](Self::planes).
+    pub fn set_plane<B: Buffer>(
+        &self,
+        plane: plane::Handle,
+        buffer: &B,
+        src: (u32, u32, u32, u32),
+        dst: (i32, i32, u32, u32),
+    ) -> Result<()> {
+        if !self.planes.contains(&plane) {
+            bail!(ErrorKind::PlaneNotCompatible(plane, self.crtc));
+        }
+
+        let fb = self
+            .dev
+            .add_framebuffer(buffer)
+            .chain_err(|| ErrorKind::DrmDev(format!("Error adding framebuffer on {:?}", self.dev_path())))?;
+        let fb = fb.handle();
+
+        let result = plane::set(
+            &*self.dev,
+            plane,
+            self.crtc,
+            fb,
+            0,
+            dst.0,
+            dst.1,
+            dst.2,
+            dst.3,
+            src.0,
+            src.1,
+            src.2,
+            src.3,
+        ).chain_err(|| ErrorKind::DrmDev(format!("Error setting plane on {:?}", self.dev_path())));
+
+        if result.is_ok() {
+            // The plane is now scanning out `fb`, so whatever we previously added for this
+            // plane is no longer needed and can be dropped instead of leaking a kernel
+            // framebuffer object on every call.
+            let old_fb = self.plane_fbs.write().unwrap().insert(plane, fb);
+            if let Some(old_fb) = old_fb {
+                if old_fb != fb {
+                    if let Err(err) = self.dev.rm_framebuffer(old_fb) {
+                        warn!(self.logger, "Failed to remove old framebuffer on plane {:?}: {}", plane, err);
+                    }
+                }
+            }
+        } else {
+            // The plane is still showing whatever `plane_fbs` already points at, so leave
+            // that entry alone and get rid of the new, never-displayed `fb` instead.
+            if let Err(err) = self.dev.rm_framebuffer(fb) {
+                warn!(self.logger, "Failed to remove unused framebuffer on plane {:?}: {}", plane, err);
+            }
+        }
+
+        result
+    }
+
+    /// Whether any connector currently driven by this surface advertises adaptive sync
+    /// support in its EDID (the `vrr_capable` connector property).
+    pub fn supports_vrr(&self) -> bool {
+        self.state.read().unwrap().connectors.iter().any(|conn| {
+            get_property_value(&*self.dev, *conn, "vrr_capable") == Some(1)
+        })
+    }
+
+    /// Enables or disables variable refresh rate on this surface's crtc.
+    ///
+    /// Once enabled, page flips are presented as soon as the client buffer is ready
+    /// instead of being latched to the fixed mode's vblank. Fails if the driver does not
+    /// expose the `VRR_ENABLED` crtc property, so compositors can fall back gracefully.
+    pub fn set_vrr(&self, enabled: bool) -> Result<()> {
+        let props = load_properties(&*self.dev, self.crtc);
+        let prop = props
+            .get("VRR_ENABLED")
+            .ok_or_else(|| Error::from(ErrorKind::UnknownProperty("VRR_ENABLED".to_string())))?;
+
+        self.dev
+            .set_property(self.crtc, prop, enabled as u64)
+            .chain_err(|| ErrorKind::DrmDev(format!("Error setting VRR_ENABLED on {:?}", self.dev_path())))
+    }
+}
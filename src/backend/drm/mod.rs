@@ -0,0 +1,131 @@
+//!
+//! Common traits and types for kernel modesetting (DRM/KMS) backends.
+//!
+//! Two implementations are provided: [`legacy`], which drives the kernel via the
+//! legacy `drmModeSetCrtc`/`drmModeSetCursor` ioctls, and [`atomic`], which batches
+//! all property changes into a single `drmModeAtomicCommit`. Most compositors should
+//! prefer the atomic backend where the driver supports it and fall back to the
+//! legacy backend otherwise, see [`fallback::FallbackDevice`].
+//!
+
+use drm::control::{connector, crtc, Mode};
+use std::path::PathBuf;
+
+pub mod atomic;
+pub mod fallback;
+pub mod legacy;
+pub(crate) mod props;
+
+/// A device that can be queried for the path it was opened from.
+///
+/// Used purely for diagnostics and error messages.
+pub trait DevPath {
+    /// Returns the path of the underlying device node, if it could be determined.
+    fn dev_path(&self) -> Option<PathBuf>;
+}
+
+impl<T: std::os::unix::io::AsRawFd> DevPath for T {
+    fn dev_path(&self) -> Option<PathBuf> {
+        use std::fs;
+        fs::read_link(format!("/proc/self/fd/{}", self.as_raw_fd())).ok()
+    }
+}
+
+/// An open drm device, that can be used to create [`Surface`]s for rendering.
+pub trait Device: AsRawFdOfDevice + DevPath {
+    /// Surfaces created by this device.
+    type Surface: Surface;
+    /// Type returned by `create_surface`, usually a (reference-counted) handle to a `Surface`.
+    type Return;
+    /// Error type returned by operations on this device.
+    type Error;
+
+    /// Sets a handler to be called for incoming events, like a finished page flip or a hotplug.
+    fn set_handler(&mut self, handler: impl DeviceHandler<Device = Self> + 'static);
+    /// Removes a previously set handler.
+    fn clear_handler(&mut self);
+
+    /// Creates a new surface on the given `crtc` driving the given `connectors` at `mode`.
+    ///
+    /// Implementations are expected to verify the requested configuration is actually
+    /// supported by the hardware (available encoders, modes, ...) before returning a surface.
+    fn create_surface(
+        &mut self,
+        crtc: crtc::Handle,
+        mode: Mode,
+        connectors: impl Into<<Self::Surface as Surface>::Connectors>,
+    ) -> Result<Self::Return, Self::Error>;
+
+    /// Processes any outstanding events (page flips, hotplugs, ...), invoking the set handler.
+    fn process_events(&mut self);
+}
+
+/// Blanket helper so `Device` does not need to repeat the `AsRawFd` bound everywhere.
+pub trait AsRawFdOfDevice: std::os::unix::io::AsRawFd {}
+impl<T: std::os::unix::io::AsRawFd> AsRawFdOfDevice for T {}
+
+/// A [`Device`] that directly represents a drm node, as opposed to e.g. an egl-wrapped one.
+pub trait RawDevice: Device<Return = std::rc::Rc<<Self as RawDevice>::Surface>> {
+    /// The concrete, raw surface type produced by this device.
+    type Surface: RawSurface;
+}
+
+/// A rendering target, usually corresponding to one CRTC of a [`Device`].
+pub trait Surface {
+    /// Collection type used to pass connectors in and out of this surface.
+    type Connectors: IntoIterator<Item = connector::Handle>;
+    /// Error type returned by operations on this surface.
+    type Error;
+
+    /// The crtc driven by this surface.
+    fn crtc(&self) -> crtc::Handle;
+    /// Connectors currently driven by this surface.
+    fn current_connectors(&self) -> Self::Connectors;
+    /// Connectors that will be driven once the pending state is applied.
+    fn pending_connectors(&self) -> Self::Connectors;
+    /// Adds a connector to the pending state of this surface.
+    fn add_connector(&self, connector: connector::Handle) -> Result<(), Self::Error>;
+    /// Removes a connector from the pending state of this surface.
+    fn remove_connector(&self, connector: connector::Handle) -> Result<(), Self::Error>;
+    /// The mode currently driven by this surface.
+    fn current_mode(&self) -> Mode;
+    /// The mode that will be driven once the pending state is applied.
+    fn pending_mode(&self) -> Mode;
+    /// Schedules a mode change to be applied once the surface commits.
+    fn use_mode(&self, mode: Mode) -> Result<(), Self::Error>;
+}
+
+/// A [`Surface`] that is a direct, raw representation of a crtc and can be committed to the kernel.
+pub trait RawSurface: Surface + AsRawFdOfDevice {
+    /// Returns whether the pending state differs from the current one and needs a [`commit`](RawSurface::commit).
+    fn commit_pending(&self) -> bool;
+    /// Applies the pending state, potentially as a full modeset.
+    fn commit(&self) -> Result<(), <Self as Surface>::Error>;
+    /// Schedules a (non-blocking) page flip of the given framebuffer.
+    fn page_flip(&self, framebuffer: u32) -> Result<(), <Self as Surface>::Error>;
+}
+
+/// Handler trait, invoked by a [`Device`] for asynchronous events.
+pub trait DeviceHandler {
+    /// The device this handler was registered on.
+    type Device: Device + ?Sized;
+
+    /// Called when a page flip scheduled on `surface` has completed and the buffer is now visible.
+    fn vblank(&mut self, surface: &<Self::Device as Device>::Surface);
+    /// Called when an error occurred while processing events for this device.
+    fn error(&mut self, error: <Self::Device as Device>::Error);
+
+    /// Called when a new connector was plugged in.
+    ///
+    /// The default implementation does nothing; compositors that want to create a surface
+    /// for newly attached monitors without polling should override this.
+    #[allow(unused_variables)]
+    fn connector_added(&mut self, connector: connector::Handle, state: connector::State) {}
+
+    /// Called when a previously connected connector was unplugged.
+    ///
+    /// The default implementation does nothing; compositors that want to tear down the
+    /// surface of a removed monitor without polling should override this.
+    #[allow(unused_variables)]
+    fn connector_removed(&mut self, connector: connector::Handle, state: connector::State) {}
+}
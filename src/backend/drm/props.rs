@@ -0,0 +1,60 @@
+//!
+//! Small helper to resolve the kernel-assigned property handles (and current values) of a
+//! crtc, connector or plane by their human readable name, so the rest of the drm backends
+//! can refer to e.g. `"MODE_ID"` or `"VRR_ENABLED"` instead of juggling raw property
+//! handles. Shared between the [`atomic`](super::atomic) backend, which relies on it for
+//! every commit, and the [`legacy`](super::legacy) backend, which only needs it for
+//! properties with no dedicated ioctl (e.g. adaptive sync).
+//!
+
+use drm::control::{property, Device as ControlDevice, ResourceHandle};
+use std::collections::HashMap;
+
+pub(in crate::backend::drm) struct PropMapping(HashMap<String, property::Handle>);
+
+impl PropMapping {
+    /// Looks up the property handle for `name` on the object this mapping was built for.
+    pub(in crate::backend::drm) fn get(&self, name: &str) -> Option<property::Handle> {
+        self.0.get(name).cloned()
+    }
+}
+
+/// Loads every property exposed by `handle` and indexes it by name.
+pub(in crate::backend::drm) fn load_properties<D: ControlDevice, H: ResourceHandle>(
+    dev: &D,
+    handle: H,
+) -> PropMapping {
+    let mut map = HashMap::new();
+
+    if let Ok(props) = dev.get_properties(handle) {
+        let (ids, _) = props.as_props_and_values();
+        for &id in ids {
+            if let Ok(info) = property::Info::load_from_device(dev, id) {
+                map.insert(info.name().to_string_lossy().into_owned(), id);
+            }
+        }
+    }
+
+    PropMapping(map)
+}
+
+/// Finds the current raw value of property `name` on `handle`, if the device exposes it.
+///
+/// Used for properties that are only ever read, such as `vrr_capable`, where looking up
+/// just the handle (as [`load_properties`] does) is not enough.
+pub(in crate::backend::drm) fn get_property_value<D: ControlDevice, H: ResourceHandle + Copy>(
+    dev: &D,
+    handle: H,
+    name: &str,
+) -> Option<u64> {
+    let props = dev.get_properties(handle).ok()?;
+    let (ids, vals) = props.as_props_and_values();
+    for (&id, &val) in ids.iter().zip(vals.iter()) {
+        if let Ok(info) = property::Info::load_from_device(dev, id) {
+            if info.name().to_string_lossy() == name {
+                return Some(val);
+            }
+        }
+    }
+    None
+}